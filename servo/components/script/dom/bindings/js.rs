@@ -14,6 +14,24 @@
 //! - `Root<T>`: a stack-based reference to a rooted DOM object.
 //! - `JS<T>`: a reference to a DOM object that can automatically be traced by
 //!   the GC when encountered as a field of a Rust structure.
+//! - `Handle<T>`/`MutableHandle<T>`: cheap, `Copy`able borrows of an
+//!   already-rooted value, for passing to and from methods that don't need
+//!   to root anything themselves.
+//! - `PersistentRoot<T>`: a heap-allocatable root for DOM objects held by
+//!   long-lived Rust structures, independent of any stack scope.
+//! - `RootedVec<T>`: a dynamically-built, stack-rooted vector of DOM
+//!   objects, rooted as a single entry rather than one `Root` per element.
+//!
+//! `MutJS<T>`, `MutNullableJS<T>`, and `OnceCellJS<T>` route every write
+//! through SpiderMonkey's generational-GC write barriers: rather than
+//! storing a `JS<T>` directly, they hold their slot as a bare
+//! `Heap<*mut JSObject>` -- the same type, and the same `set`/`get` calls,
+//! that `Reflector` already uses for its own `JSObject*` -- so retargeting
+//! the slot always goes through `Heap::set`'s pre/post barrier instead of a
+//! raw write. Recovering `&T` back out of the stored `JSObject*` goes
+//! through `dom::bindings::conversions::private_from_object`, the same
+//! private-slot unwrap the rest of the bindings use to get from a
+//! `JSObject*` back to its native Rust object.
 //!
 //! `JS<T>` does not allow access to their inner value without explicitly
 //! creating a stack-based root via the `root` method. This returns a `Root<T>`,
@@ -24,7 +42,7 @@
 //!
 
 use core::nonzero::NonZero;
-use dom::bindings::conversions::DerivedFrom;
+use dom::bindings::conversions::{DerivedFrom, private_from_object};
 use dom::bindings::inheritance::Castable;
 use dom::bindings::reflector::{DomObject, Reflector};
 use dom::bindings::trace::JSTraceable;
@@ -36,17 +54,41 @@ use js::rust::GCMethods;
 use mitochondria::OnceCell;
 use script_layout_interface::TrustedNodeAddress;
 use script_thread::STACK_ROOTS;
-use std::cell::UnsafeCell;
+use std::cell::Cell;
 use std::default::Default;
 use std::hash::{Hash, Hasher};
 #[cfg(debug_assertions)]
 use std::intrinsics::type_name;
+use std::marker::PhantomData;
 use std::mem;
 use std::ops::Deref;
+use std::ops::Index;
 use std::ptr;
 use std::rc::Rc;
+use std::slice;
 use style::thread_state;
 
+/// Assert that the current thread is the script thread.
+///
+/// This is factored out of the (generic) methods on `JS<T>`, `LayoutJS<T>`,
+/// `MutJS<T>`, `MutNullableJS<T>`, `OnceCellJS<T>`, `Root<T>` and
+/// `RootCollection` so the `thread_state` fetch and bitflag test are emitted
+/// once, rather than duplicated in every monomorphization of those generics.
+///
+/// Deliberately not `#[inline]`: that would just invite the optimizer to
+/// copy the body back into every caller, undoing the point of factoring it
+/// out as a shared, non-generic function.
+fn assert_in_script() {
+    debug_assert!(thread_state::get().is_script());
+}
+
+/// Assert that the current thread is the layout thread. See
+/// `assert_in_script` for why this is a free function rather than inlined
+/// into each generic method.
+fn assert_in_layout() {
+    debug_assert!(thread_state::get().is_layout());
+}
+
 /// A traced reference to a DOM object
 ///
 /// This type is critical to making garbage collection work with the DOM,
@@ -70,7 +112,7 @@ impl<T> HeapSizeOf for JS<T> {
 impl<T> JS<T> {
     /// Returns `LayoutJS<T>` containing the same pointer.
     pub unsafe fn to_layout(&self) -> LayoutJS<T> {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         LayoutJS {
             ptr: self.ptr.clone(),
         }
@@ -81,11 +123,16 @@ impl<T: DomObject> JS<T> {
     /// Create a JS<T> from a &T
     #[allow(unrooted_must_root)]
     pub fn from_ref(obj: &T) -> JS<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         JS {
             ptr: unsafe { NonZero::new_unchecked(&*obj) },
         }
     }
+
+    /// Obtain a `Handle` to this value without creating a fresh `Root`.
+    pub fn handle(&self) -> Handle<T> {
+        Handle::new(&*self)
+    }
 }
 
 impl<'root, T: DomObject + 'root> RootedReference<'root> for JS<T> {
@@ -99,7 +146,7 @@ impl<T: DomObject> Deref for JS<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         // We can only have &JS<T> from a rooted thing, so it's safe to deref
         // it to &T.
         unsafe { &*self.ptr.get() }
@@ -134,7 +181,7 @@ impl<T: Castable> LayoutJS<T> {
         where U: Castable,
               T: DerivedFrom<U>
     {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         let ptr: *const T = self.ptr.get();
         LayoutJS {
             ptr: unsafe { NonZero::new_unchecked(ptr as *const U) },
@@ -145,7 +192,7 @@ impl<T: Castable> LayoutJS<T> {
     pub fn downcast<U>(&self) -> Option<LayoutJS<U>>
         where U: DerivedFrom<T>
     {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         unsafe {
             if (*self.unsafe_get()).is::<U>() {
                 let ptr: *const T = self.ptr.get();
@@ -162,7 +209,7 @@ impl<T: Castable> LayoutJS<T> {
 impl<T: DomObject> LayoutJS<T> {
     /// Get the reflector.
     pub unsafe fn get_jsobject(&self) -> *mut JSObject {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         (*self.ptr.get()).reflector().get_jsobject().get()
     }
 }
@@ -201,7 +248,7 @@ impl <T> Clone for JS<T> {
     #[inline]
     #[allow(unrooted_must_root)]
     fn clone(&self) -> JS<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         JS {
             ptr: self.ptr.clone(),
         }
@@ -211,7 +258,7 @@ impl <T> Clone for JS<T> {
 impl <T> Clone for LayoutJS<T> {
     #[inline]
     fn clone(&self) -> LayoutJS<T> {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         LayoutJS {
             ptr: self.ptr.clone(),
         }
@@ -222,7 +269,7 @@ impl LayoutJS<Node> {
     /// Create a new JS-owned value wrapped from an address known to be a
     /// `Node` pointer.
     pub unsafe fn from_trusted_node_address(inner: TrustedNodeAddress) -> LayoutJS<Node> {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         let TrustedNodeAddress(addr) = inner;
         LayoutJS {
             ptr: NonZero::new_unchecked(addr as *const Node),
@@ -233,38 +280,52 @@ impl LayoutJS<Node> {
 /// A holder that provides interior mutability for GC-managed values such as
 /// `JS<T>`.  Essentially a `Cell<JS<T>>`, but safer.
 ///
+/// Unlike `JS<T>`, this stores its target as a bare `Heap<*mut JSObject>`
+/// rather than a `JS<T>`, so `set` always goes through `Heap::set`'s
+/// pre/post write barrier instead of overwriting the slot directly.
+///
 /// This should only be used as a field in other DOM objects; see warning
 /// on `JS<T>`.
 #[must_root]
-#[derive(JSTraceable)]
 pub struct MutJS<T: DomObject> {
-    val: UnsafeCell<JS<T>>,
+    val: Heap<*mut JSObject>,
+    _marker: PhantomData<T>,
 }
 
 impl<T: DomObject> MutJS<T> {
     /// Create a new `MutJS`.
     pub fn new(initial: &T) -> MutJS<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
+        let val = Heap::default();
+        val.set(initial.reflector().get_jsobject().get());
         MutJS {
-            val: UnsafeCell::new(JS::from_ref(initial)),
+            val: val,
+            _marker: PhantomData,
         }
     }
 
     /// Set this `MutJS` to the given value.
+    ///
+    /// This goes through `Heap<*mut JSObject>::set`, the same call
+    /// `Reflector::set_jsobject` uses, so the pre/post write barriers for
+    /// an incremental or generational collector fire on every retarget.
     pub fn set(&self, val: &T) {
-        debug_assert!(thread_state::get().is_script());
-        unsafe {
-            *self.val.get() = JS::from_ref(val);
-        }
+        assert_in_script();
+        self.val.set(val.reflector().get_jsobject().get());
     }
 
     /// Get the value in this `MutJS`.
     pub fn get(&self) -> Root<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         unsafe {
-            Root::from_ref(&*ptr::read(self.val.get()))
+            Root::from_ref(&*(private_from_object(self.val.get()) as *const T))
         }
     }
+
+    /// Obtain a `MutableHandle` to this slot, for use as an out-parameter.
+    pub fn handle(&self) -> MutableHandle<T> {
+        MutableHandle::new(self)
+    }
 }
 
 impl<T: DomObject> HeapSizeOf for MutJS<T> {
@@ -274,40 +335,62 @@ impl<T: DomObject> HeapSizeOf for MutJS<T> {
     }
 }
 
+#[allow(unrooted_must_root)]
+unsafe impl<T: DomObject> JSTraceable for MutJS<T> {
+    unsafe fn trace(&self, trc: *mut JSTracer) {
+        #[cfg(debug_assertions)]
+        let trace_str = format!("for {} on heap", type_name::<T>());
+        #[cfg(debug_assertions)]
+        let trace_info = &trace_str[..];
+        #[cfg(not(debug_assertions))]
+        let trace_info = "for DOM object on heap";
+
+        trace_reflector(trc,
+                        trace_info,
+                        (*(private_from_object(self.val.get()) as *const T)).reflector());
+    }
+}
+
 impl<T: DomObject> PartialEq for MutJS<T> {
    fn eq(&self, other: &Self) -> bool {
-        unsafe {
-            *self.val.get() == *other.val.get()
-        }
+        self.val.get() == other.val.get()
     }
 }
 
 impl<T: DomObject + PartialEq> PartialEq<T> for MutJS<T> {
     fn eq(&self, other: &T) -> bool {
-        unsafe {
-            **self.val.get() == *other
-        }
+        self.val.get() == other.reflector().get_jsobject().get()
     }
 }
 
 /// A holder that provides interior mutability for GC-managed values such as
-/// `JS<T>`, with nullability represented by an enclosing Option wrapper.
-/// Essentially a `Cell<Option<JS<T>>>`, but safer.
+/// `JS<T>`, with nullability represented by a null `JSObject*` rather than
+/// an enclosing `Option` wrapper. Essentially a `Cell<Option<JS<T>>>`, but
+/// safer.
+///
+/// Like `MutJS<T>`, this stores its target as a bare `Heap<*mut JSObject>`,
+/// so `set` always goes through `Heap::set`'s pre/post write barrier
+/// instead of overwriting the slot directly.
 ///
 /// This should only be used as a field in other DOM objects; see warning
 /// on `JS<T>`.
 #[must_root]
-#[derive(JSTraceable)]
 pub struct MutNullableJS<T: DomObject> {
-    ptr: UnsafeCell<Option<JS<T>>>,
+    ptr: Heap<*mut JSObject>,
+    _marker: PhantomData<T>,
 }
 
 impl<T: DomObject> MutNullableJS<T> {
     /// Create a new `MutNullableJS`.
     pub fn new(initial: Option<&T>) -> MutNullableJS<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
+        let ptr = Heap::default();
+        if let Some(obj) = initial {
+            ptr.set(obj.reflector().get_jsobject().get());
+        }
         MutNullableJS {
-            ptr: UnsafeCell::new(initial.map(JS::from_ref)),
+            ptr: ptr,
+            _marker: PhantomData,
         }
     }
 
@@ -316,7 +399,7 @@ impl<T: DomObject> MutNullableJS<T> {
     pub fn or_init<F>(&self, cb: F) -> Root<T>
         where F: FnOnce() -> Root<T>
     {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         match self.get() {
             Some(inner) => inner,
             None => {
@@ -329,26 +412,41 @@ impl<T: DomObject> MutNullableJS<T> {
 
     /// Retrieve a copy of the inner optional `JS<T>` as `LayoutJS<T>`.
     /// For use by layout, which can't use safe types like Temporary.
-    #[allow(unrooted_must_root)]
     pub unsafe fn get_inner_as_layout(&self) -> Option<LayoutJS<T>> {
-        debug_assert!(thread_state::get().is_layout());
-        ptr::read(self.ptr.get()).map(|js| js.to_layout())
+        assert_in_layout();
+        let obj = self.ptr.get();
+        if obj.is_null() {
+            None
+        } else {
+            Some(LayoutJS {
+                ptr: NonZero::new_unchecked(private_from_object(obj) as *const T),
+            })
+        }
     }
 
     /// Get a rooted value out of this object
-    #[allow(unrooted_must_root)]
     pub fn get(&self) -> Option<Root<T>> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         unsafe {
-            ptr::read(self.ptr.get()).map(|o| Root::from_ref(&*o))
+            let obj = self.ptr.get();
+            if obj.is_null() {
+                None
+            } else {
+                Some(Root::from_ref(&*(private_from_object(obj) as *const T)))
+            }
         }
     }
 
     /// Set this `MutNullableJS` to the given value.
+    ///
+    /// As with `MutJS::set`, this goes through `Heap<*mut JSObject>::set`,
+    /// so the pre/post write barriers for an incremental or generational
+    /// collector fire on every retarget.
     pub fn set(&self, val: Option<&T>) {
-        debug_assert!(thread_state::get().is_script());
-        unsafe {
-            *self.ptr.get() = val.map(|p| JS::from_ref(p));
+        assert_in_script();
+        match val {
+            Some(obj) => self.ptr.set(obj.reflector().get_jsobject().get()),
+            None => self.ptr.set(ptr::null_mut()),
         }
     }
 
@@ -360,28 +458,46 @@ impl<T: DomObject> MutNullableJS<T> {
     }
 }
 
+#[allow(unrooted_must_root)]
+unsafe impl<T: DomObject> JSTraceable for MutNullableJS<T> {
+    unsafe fn trace(&self, trc: *mut JSTracer) {
+        let obj = self.ptr.get();
+        if !obj.is_null() {
+            #[cfg(debug_assertions)]
+            let trace_str = format!("for {} on heap", type_name::<T>());
+            #[cfg(debug_assertions)]
+            let trace_info = &trace_str[..];
+            #[cfg(not(debug_assertions))]
+            let trace_info = "for DOM object on heap";
+
+            trace_reflector(trc,
+                            trace_info,
+                            (*(private_from_object(obj) as *const T)).reflector());
+        }
+    }
+}
+
 impl<T: DomObject> PartialEq for MutNullableJS<T> {
     fn eq(&self, other: &Self) -> bool {
-        unsafe {
-            *self.ptr.get() == *other.ptr.get()
-        }
+        self.ptr.get() == other.ptr.get()
     }
 }
 
 impl<'a, T: DomObject> PartialEq<Option<&'a T>> for MutNullableJS<T> {
     fn eq(&self, other: &Option<&T>) -> bool {
-        unsafe {
-            *self.ptr.get() == other.map(JS::from_ref)
+        self.ptr.get() == match *other {
+            Some(obj) => obj.reflector().get_jsobject().get(),
+            None => ptr::null_mut(),
         }
     }
 }
 
 impl<T: DomObject> Default for MutNullableJS<T> {
-    #[allow(unrooted_must_root)]
     fn default() -> MutNullableJS<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         MutNullableJS {
-            ptr: UnsafeCell::new(None),
+            ptr: Heap::default(),
+            _marker: PhantomData,
         }
     }
 }
@@ -397,31 +513,40 @@ impl<T: DomObject> HeapSizeOf for MutNullableJS<T> {
 /// `JS<T>`, using OnceCell
 /// Essentially a `OnceCell<JS<T>>`.
 ///
+/// Like `MutJS<T>`, this stores its target as a bare `Heap<*mut JSObject>`,
+/// so the initializing write goes through `Heap::set`'s pre/post write
+/// barrier instead of writing the slot directly.
+///
 /// This should only be used as a field in other DOM objects; see warning
 /// on `JS<T>`.
 #[must_root]
 pub struct OnceCellJS<T: DomObject> {
-    ptr: OnceCell<JS<T>>,
+    ptr: OnceCell<Heap<*mut JSObject>>,
+    _marker: PhantomData<T>,
 }
 
 impl<T: DomObject> OnceCellJS<T> {
     /// Retrieve a copy of the current inner value. If it is `None`, it is
     /// initialized with the result of `cb` first.
-    #[allow(unrooted_must_root)]
     pub fn init_once<F>(&self, cb: F) -> &T
         where F: FnOnce() -> Root<T>
     {
-        debug_assert!(thread_state::get().is_script());
-        &self.ptr.init_once(|| JS::from_ref(&cb()))
+        assert_in_script();
+        let heap = self.ptr.init_once(|| {
+            let heap = Heap::default();
+            heap.set(cb().reflector().get_jsobject().get());
+            heap
+        });
+        unsafe { &*(private_from_object(heap.get()) as *const T) }
     }
 }
 
 impl<T: DomObject> Default for OnceCellJS<T> {
-    #[allow(unrooted_must_root)]
     fn default() -> OnceCellJS<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         OnceCellJS {
             ptr: OnceCell::new(),
+            _marker: PhantomData,
         }
     }
 }
@@ -433,11 +558,19 @@ impl<T: DomObject> HeapSizeOf for OnceCellJS<T> {
     }
 }
 
-#[allow(unrooted_must_root)]
 unsafe impl<T: DomObject> JSTraceable for OnceCellJS<T> {
     unsafe fn trace(&self, trc: *mut JSTracer) {
-        if let Some(ptr) = self.ptr.as_ref() {
-            ptr.trace(trc);
+        if let Some(heap) = self.ptr.as_ref() {
+            #[cfg(debug_assertions)]
+            let trace_str = format!("for {} on heap", type_name::<T>());
+            #[cfg(debug_assertions)]
+            let trace_info = &trace_str[..];
+            #[cfg(not(debug_assertions))]
+            let trace_info = "for DOM object on heap";
+
+            trace_reflector(trc,
+                            trace_info,
+                            (*(private_from_object(heap.get()) as *const T)).reflector());
         }
     }
 }
@@ -447,7 +580,7 @@ impl<T: DomObject> LayoutJS<T> {
     /// the only method that be safely accessed from layout. (The fact that
     /// this is unsafe is what necessitates the layout wrappers.)
     pub unsafe fn unsafe_get(&self) -> *const T {
-        debug_assert!(thread_state::get().is_layout());
+        assert_in_layout();
         self.ptr.get()
     }
 
@@ -455,7 +588,7 @@ impl<T: DomObject> LayoutJS<T> {
     /// safe to call because it originates from the layout thread, and it cannot
     /// mutate DOM nodes.
     pub fn get_for_script(&self) -> &T {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         unsafe { &*self.ptr.get() }
     }
 }
@@ -489,13 +622,136 @@ impl<'root, T: RootedReference<'root> + 'root> RootedReference<'root> for Option
     }
 }
 
+/// A reference to a DOM object known to be rooted for at least
+/// `'root`, mirroring SpiderMonkey's `Handle` (see `RootingAPI.h`).
+///
+/// Unlike `Root<T>`, a `Handle` is a plain `Copy` borrow: it is produced only
+/// from something that is already rooted (`Root::handle`, `JS::handle`, or
+/// an existing `Handle`'s own `upcast`/`downcast`), and does not itself
+/// participate in rooting. DOM methods should prefer taking `Handle<T>` by
+/// value over `&Root<T>` when the caller is known to already hold a root.
+pub struct Handle<'root, T: 'root> {
+    ptr: &'root T,
+}
+
+impl<'root, T> Handle<'root, T> {
+    fn new(ptr: &'root T) -> Handle<'root, T> {
+        Handle {
+            ptr: ptr,
+        }
+    }
+}
+
+impl<'root, T> Copy for Handle<'root, T> {}
+
+impl<'root, T> Clone for Handle<'root, T> {
+    fn clone(&self) -> Handle<'root, T> {
+        *self
+    }
+}
+
+impl<'root, T> Deref for Handle<'root, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.ptr
+    }
+}
+
+impl<'root, T: Castable> Handle<'root, T> {
+    /// Cast a handle upwards to one of the interfaces it derives from.
+    pub fn upcast<U>(self) -> Handle<'root, U>
+        where U: Castable,
+              T: DerivedFrom<U>
+    {
+        unsafe { mem::transmute(self) }
+    }
+
+    /// Cast a handle downwards to one of the interfaces it might implement.
+    pub fn downcast<U>(self) -> Option<Handle<'root, U>>
+        where U: DerivedFrom<T>
+    {
+        if self.ptr.is::<U>() {
+            Some(unsafe { mem::transmute(self) })
+        } else {
+            None
+        }
+    }
+}
+
+/// A mutable out-parameter handle onto a `MutJS<T>` slot, mirroring
+/// SpiderMonkey's `MutableHandle` (see `RootingAPI.h`).
+pub struct MutableHandle<'root, T: DomObject + 'root> {
+    ptr: &'root MutJS<T>,
+}
+
+impl<'root, T: DomObject> MutableHandle<'root, T> {
+    fn new(ptr: &'root MutJS<T>) -> MutableHandle<'root, T> {
+        MutableHandle {
+            ptr: ptr,
+        }
+    }
+
+    /// Set the value pointed to by this handle.
+    pub fn set(&self, val: &T) {
+        self.ptr.set(val);
+    }
+
+    /// Get a rooted copy of the value pointed to by this handle.
+    pub fn get(&self) -> Root<T> {
+        self.ptr.get()
+    }
+}
+
+impl<'root, T: DomObject> Copy for MutableHandle<'root, T> {}
+
+impl<'root, T: DomObject> Clone for MutableHandle<'root, T> {
+    fn clone(&self) -> MutableHandle<'root, T> {
+        *self
+    }
+}
+
+/// An intrusive doubly-linked list node threading a `Root<T>` into its
+/// `RootCollection`, so that rooting and unrooting don't require a linear
+/// scan of every other root on the stack.
+///
+/// `Root<T>` owns its entry through a `Box`, which is what gives the node a
+/// stable heap address that survives the `Root<T>` itself being moved
+/// (returned by value, stored in a `Vec<Root<T>>`, etc.) — only the `Box`'s
+/// pointer moves, never the `RootListEntry` it points at, so the list's
+/// `prev`/`next` links (and the `RootCollection`'s head) never go stale.
+/// Rooting is not LIFO, so removal must work from the middle of the list,
+/// not just the head.
+struct RootListEntry {
+    prev: Cell<*mut RootListEntry>,
+    next: Cell<*mut RootListEntry>,
+    reflector: *const Reflector,
+}
+
+/// An intrusive doubly-linked list node threading a rooted *container* (e.g.
+/// `RootedVec<T>`) into its `RootCollection`.
+///
+/// Unlike `RootListEntry`, which pins a single reflector, a
+/// `TraceableRootListEntry` points back at its owning container through the
+/// `JSTraceable` trait object, so one list entry can trace an arbitrary
+/// number of reflectors (e.g. every element of a `RootedVec<T>`) instead of
+/// requiring one list entry per element. As with `RootListEntry`, the entry
+/// lives behind a `Box` owned by its container (see `RootedVec<T>`'s `entry`
+/// field), so its address stays stable no matter how the container itself
+/// is moved.
+struct TraceableRootListEntry {
+    prev: Cell<*mut TraceableRootListEntry>,
+    next: Cell<*mut TraceableRootListEntry>,
+    traceable: *const JSTraceable,
+}
+
 /// A rooting mechanism for reflectors on the stack.
 /// LIFO is not required.
 ///
 /// See also [*Exact Stack Rooting - Storing a GCPointer on the CStack*]
 /// (https://developer.mozilla.org/en-US/docs/Mozilla/Projects/SpiderMonkey/Internals/GC/Exact_Stack_Rooting).
 pub struct RootCollection {
-    roots: UnsafeCell<Vec<*const Reflector>>,
+    head: Cell<*mut RootListEntry>,
+    traceable_head: Cell<*mut TraceableRootListEntry>,
 }
 
 /// A pointer to a RootCollection, for use in global variables.
@@ -511,31 +767,85 @@ impl Clone for RootCollectionPtr {
 impl RootCollection {
     /// Create an empty collection of roots
     pub fn new() -> RootCollection {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         RootCollection {
-            roots: UnsafeCell::new(vec![]),
+            head: Cell::new(ptr::null_mut()),
+            traceable_head: Cell::new(ptr::null_mut()),
         }
     }
 
-    /// Start tracking a stack-based root
-    unsafe fn root(&self, untracked_reflector: *const Reflector) {
-        debug_assert!(thread_state::get().is_script());
-        let roots = &mut *self.roots.get();
-        roots.push(untracked_reflector);
-        assert!(!(*untracked_reflector).get_jsobject().is_null())
+    /// Splice a stack-based root's list entry onto the head of the list.
+    /// `entry` must point at a heap-allocated `RootListEntry` (see
+    /// `Root<T>`'s `entry` field) so its address stays stable for as long
+    /// as it remains linked, regardless of what happens to the `Root<T>`
+    /// that owns it.
+    unsafe fn root(&self, entry: *mut RootListEntry) {
+        assert_in_script();
+        assert!(!(*(*entry).reflector).get_jsobject().is_null());
+        let old_head = self.head.get();
+        (*entry).prev.set(ptr::null_mut());
+        (*entry).next.set(old_head);
+        if !old_head.is_null() {
+            (*old_head).prev.set(entry);
+        }
+        self.head.set(entry);
     }
 
-    /// Stop tracking a stack-based reflector, asserting if it isn't found.
-    unsafe fn unroot(&self, tracked_reflector: *const Reflector) {
-        assert!(!tracked_reflector.is_null());
-        assert!(!(*tracked_reflector).get_jsobject().is_null());
-        debug_assert!(thread_state::get().is_script());
-        let roots = &mut *self.roots.get();
-        match roots.iter().rposition(|r| *r == tracked_reflector) {
-            Some(idx) => {
-                roots.remove(idx);
-            },
-            None => panic!("Can't remove a root that was never rooted!"),
+    /// Splice a stack-based root's list entry out of the list, wherever in
+    /// the list it happens to be.
+    ///
+    /// This splicing is the same shape as `root_traceable`/
+    /// `unroot_traceable` below (see their test in the `tests` module for
+    /// a non-LIFO removal check), but isn't covered by an equivalent test
+    /// itself: exercising it needs a `RootListEntry` with a live
+    /// `reflector`, and constructing a real `Reflector`/`JSObject` pair
+    /// needs the full bindings/JSAPI setup, which isn't available to a
+    /// unit test in this module.
+    unsafe fn unroot(&self, entry: *mut RootListEntry) {
+        assert!(!(*entry).reflector.is_null());
+        assert!(!(*(*entry).reflector).get_jsobject().is_null());
+        assert_in_script();
+        let prev = (*entry).prev.get();
+        let next = (*entry).next.get();
+        if prev.is_null() {
+            self.head.set(next);
+        } else {
+            (*prev).next.set(next);
+        }
+        if !next.is_null() {
+            (*next).prev.set(prev);
+        }
+    }
+
+    /// Splice a rooted container's list entry onto the head of the
+    /// traceable list. `entry` must point at a heap-allocated
+    /// `TraceableRootListEntry` so its address stays stable for as long as
+    /// it remains linked, regardless of what happens to the container that
+    /// owns it.
+    unsafe fn root_traceable(&self, entry: *mut TraceableRootListEntry) {
+        assert_in_script();
+        let old_head = self.traceable_head.get();
+        (*entry).prev.set(ptr::null_mut());
+        (*entry).next.set(old_head);
+        if !old_head.is_null() {
+            (*old_head).prev.set(entry);
+        }
+        self.traceable_head.set(entry);
+    }
+
+    /// Splice a rooted container's list entry out of the traceable list,
+    /// wherever in the list it happens to be.
+    unsafe fn unroot_traceable(&self, entry: *mut TraceableRootListEntry) {
+        assert_in_script();
+        let prev = (*entry).prev.get();
+        let next = (*entry).next.get();
+        if prev.is_null() {
+            self.traceable_head.set(next);
+        } else {
+            (*prev).next.set(next);
+        }
+        if !next.is_null() {
+            (*next).prev.set(prev);
         }
     }
 }
@@ -545,9 +855,15 @@ pub unsafe fn trace_roots(tracer: *mut JSTracer) {
     debug!("tracing stack roots");
     STACK_ROOTS.with(|ref collection| {
         let RootCollectionPtr(collection) = collection.get().unwrap();
-        let collection = &*(*collection).roots.get();
-        for root in collection {
-            trace_reflector(tracer, "on stack", &**root);
+        let mut entry = (*collection).head.get();
+        while !entry.is_null() {
+            trace_reflector(tracer, "on stack", &*(*entry).reflector);
+            entry = (*entry).next.get();
+        }
+        let mut entry = (*collection).traceable_head.get();
+        while !entry.is_null() {
+            (*(*entry).traceable).trace(tracer);
+            entry = (*entry).next.get();
         }
     });
 }
@@ -558,8 +874,16 @@ pub unsafe fn trace_roots(tracer: *mut JSTracer) {
 /// are additive, so this object's destruction will not invalidate other roots
 /// for the same JS value. `Root`s cannot outlive the associated
 /// `RootCollection` object.
+///
+/// `entry` links this root into its `RootCollection`'s intrusive list. It is
+/// boxed so the list node has a stable heap address independent of wherever
+/// this `Root<T>` itself lives — `Root<T>` is an ordinary, freely movable
+/// value (it can be returned by value, stored in a `Vec<Root<T>>`, etc.);
+/// only the `Box`'s pointer moves along with it, never the linked node.
 #[allow_unrooted_interior]
 pub struct Root<T: DomObject> {
+    /// Heap-allocated intrusive link into the owning `RootCollection`'s list.
+    entry: Box<RootListEntry>,
     /// Reference to rooted value that must not outlive this container
     ptr: NonZero<*const T>,
     /// List that ensures correct dynamic root ordering
@@ -592,11 +916,21 @@ impl<T: DomObject> Root<T> {
     /// It cannot outlive its associated `RootCollection`, and it gives
     /// out references which cannot outlive this new `Root`.
     pub fn new(unrooted: NonZero<*const T>) -> Root<T> {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         STACK_ROOTS.with(|ref collection| {
             let RootCollectionPtr(collection) = collection.get().unwrap();
-            unsafe { (*collection).root(&*(*unrooted.get()).reflector()) }
+            // Heap-allocate the list node before linking it in, so its
+            // address is already final (and will stay that way for as long
+            // as this `Box` lives) rather than being the address of a local
+            // that the `Root` we're about to return will move out of.
+            let entry = Box::new(RootListEntry {
+                prev: Cell::new(ptr::null_mut()),
+                next: Cell::new(ptr::null_mut()),
+                reflector: unsafe { &*(*unrooted.get()).reflector() },
+            });
+            unsafe { (*collection).root(&*entry as *const _ as *mut _) }
             Root {
+                entry: entry,
                 ptr: unrooted,
                 root_list: collection,
             }
@@ -607,6 +941,12 @@ impl<T: DomObject> Root<T> {
     pub fn from_ref(unrooted: &T) -> Root<T> {
         Root::new(unsafe { NonZero::new_unchecked(unrooted) })
     }
+
+    /// Obtain a `Handle` to this root, for passing to methods that only
+    /// need to observe an already-rooted value.
+    pub fn handle(&self) -> Handle<T> {
+        Handle::new(&*self)
+    }
 }
 
 impl<'root, T: DomObject + 'root> RootedReference<'root> for Root<T> {
@@ -619,7 +959,7 @@ impl<'root, T: DomObject + 'root> RootedReference<'root> for Root<T> {
 impl<T: DomObject> Deref for Root<T> {
     type Target = T;
     fn deref(&self) -> &T {
-        debug_assert!(thread_state::get().is_script());
+        assert_in_script();
         unsafe { &*self.ptr.get() }
     }
 }
@@ -645,7 +985,7 @@ impl<T: DomObject> Clone for Root<T> {
 impl<T: DomObject> Drop for Root<T> {
     fn drop(&mut self) {
         unsafe {
-            (*self.root_list).unroot(self.reflector());
+            (*self.root_list).unroot(&mut *self.entry as *mut _);
         }
     }
 }
@@ -656,6 +996,277 @@ unsafe impl<T: DomObject> JSTraceable for Root<T> {
     }
 }
 
+/// The heap-allocated state backing a `RootedVec<T>`: the traceable list
+/// entry and the vector it traces live in the same `Box`, so that `entry`'s
+/// `traceable` pointer — which points back at this struct itself — stays
+/// valid no matter how the `RootedVec<T>` handle that owns the `Box` is
+/// moved around.
+struct RootedVecEntry<T: DomObject> {
+    entry: TraceableRootListEntry,
+    v: Vec<JS<T>>,
+}
+
+unsafe impl<T: DomObject> JSTraceable for RootedVecEntry<T> {
+    unsafe fn trace(&self, trc: *mut JSTracer) {
+        for elem in &self.v {
+            elem.trace(trc);
+        }
+    }
+}
+
+/// A vector of DOM objects rooted as a single stack root, for dynamically
+/// built collections that can't be rooted one element at a time ahead of
+/// time (e.g. nodes collected while walking the DOM tree).
+///
+/// Borrows the `GCVector`/rooted-container idea from SpiderMonkey's
+/// `GCPolicy` (see `RootingAPI.h`): registers itself with the enclosing
+/// `RootCollection` once, via `TraceableRootListEntry`, so every element is
+/// traced through a single list entry rather than needing a separate
+/// `Root<T>` per element.
+///
+/// The entry and the vector it traces live behind a `Box` (`entry`), so a
+/// `RootedVec<T>` is an ordinary, freely movable handle — only the `Box`'s
+/// pointer moves with it, never the heap-allocated `RootedVecEntry<T>` that
+/// is actually linked into the `RootCollection`.
+#[allow_unrooted_interior]
+pub struct RootedVec<T: DomObject> {
+    entry: Box<RootedVecEntry<T>>,
+    root_list: *const RootCollection,
+}
+
+unsafe impl<T: DomObject> JSTraceable for RootedVec<T> {
+    unsafe fn trace(&self, _: *mut JSTracer) {
+        // Already traced: elements are reached through `traceable_head`,
+        // same as `Root<T>`'s own no-op impl.
+    }
+}
+
+impl<T: DomObject> RootedVec<T> {
+    /// Create a new, empty `RootedVec`, registered with the stack's
+    /// `RootCollection`.
+    pub fn new() -> RootedVec<T> {
+        assert_in_script();
+        STACK_ROOTS.with(|ref collection| {
+            let RootCollectionPtr(collection) = collection.get().unwrap();
+            // Heap-allocate the entry (and the vector it traces) before
+            // linking it in, so `traceable`'s self-pointer and the address
+            // handed to `RootCollection` are both already final.
+            let mut entry = Box::new(RootedVecEntry {
+                entry: TraceableRootListEntry {
+                    prev: Cell::new(ptr::null_mut()),
+                    next: Cell::new(ptr::null_mut()),
+                    traceable: ptr::null(),
+                },
+                v: vec![],
+            });
+            let traceable: *const JSTraceable = &*entry;
+            entry.entry.traceable = traceable;
+            unsafe { (*collection).root_traceable(&mut entry.entry as *mut _) }
+            RootedVec {
+                entry: entry,
+                root_list: collection,
+            }
+        })
+    }
+
+    /// Root `val` and append it to this vector.
+    #[allow(unrooted_must_root)]
+    pub fn push(&mut self, val: &T) {
+        assert_in_script();
+        self.entry.v.push(JS::from_ref(val));
+    }
+
+    /// Iterate over the rooted elements of this vector.
+    pub fn iter(&self) -> RootedVecIter<T> {
+        RootedVecIter {
+            inner: self.entry.v.iter(),
+        }
+    }
+}
+
+impl<T: DomObject + HeapSizeOf> HeapSizeOf for RootedVec<T> {
+    fn heap_size_of_children(&self) -> usize {
+        self.entry.v.heap_size_of_children()
+    }
+}
+
+impl<T: DomObject> Index<usize> for RootedVec<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &T {
+        &self.entry.v[index]
+    }
+}
+
+impl<T: DomObject> Drop for RootedVec<T> {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.root_list).unroot_traceable(&mut self.entry.entry as *mut _);
+        }
+    }
+}
+
+/// An iterator over the rooted elements of a `RootedVec<T>`.
+pub struct RootedVecIter<'a, T: DomObject + 'a> {
+    inner: slice::Iter<'a, JS<T>>,
+}
+
+impl<'a, T: DomObject> Iterator for RootedVecIter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next().map(|js| &**js)
+    }
+}
+
+/// An intrusive doubly-linked list node threading a `PersistentRoot<T>` into
+/// its `PersistentRootCollection`, mirroring `RootListEntry` (see
+/// `RootCollection`): rooting and unrooting a persistent root must not
+/// degrade to a linear scan, since long-lived structures (timers, caches,
+/// pending callbacks) can plausibly accumulate far more persistent roots
+/// than any one stack frame ever holds.
+///
+/// As with `RootListEntry`, this node is boxed by its owning
+/// `PersistentRoot<T>` so its address stays stable no matter how the
+/// `PersistentRoot<T>` itself is moved.
+struct PersistentRootListEntry {
+    prev: Cell<*mut PersistentRootListEntry>,
+    next: Cell<*mut PersistentRootListEntry>,
+    reflector: *const Reflector,
+}
+
+/// A collection of persistent roots, analogous to `RootCollection` but not
+/// tied to any one stack frame or task: entries are registered and
+/// unregistered independently as `PersistentRoot<T>`s are created and
+/// dropped, for as long as the thread (and its JS runtime) lives.
+struct PersistentRootCollection {
+    head: Cell<*mut PersistentRootListEntry>,
+}
+
+impl PersistentRootCollection {
+    fn new() -> PersistentRootCollection {
+        PersistentRootCollection {
+            head: Cell::new(ptr::null_mut()),
+        }
+    }
+
+    /// Splice a persistent root's list entry onto the head of the list.
+    /// `entry` must point at a heap-allocated `PersistentRootListEntry` (see
+    /// `PersistentRoot<T>`'s `entry` field) so its address stays stable for
+    /// as long as it remains linked.
+    unsafe fn root(&self, entry: *mut PersistentRootListEntry) {
+        assert_in_script();
+        assert!(!(*(*entry).reflector).get_jsobject().is_null());
+        let old_head = self.head.get();
+        (*entry).prev.set(ptr::null_mut());
+        (*entry).next.set(old_head);
+        if !old_head.is_null() {
+            (*old_head).prev.set(entry);
+        }
+        self.head.set(entry);
+    }
+
+    /// Splice a persistent root's list entry out of the list, wherever in
+    /// the list it happens to be.
+    unsafe fn unroot(&self, entry: *mut PersistentRootListEntry) {
+        assert_in_script();
+        let prev = (*entry).prev.get();
+        let next = (*entry).next.get();
+        if prev.is_null() {
+            self.head.set(next);
+        } else {
+            (*prev).next.set(next);
+        }
+        if !next.is_null() {
+            (*next).prev.set(prev);
+        }
+    }
+}
+
+thread_local!(static PERSISTENT_ROOTS: PersistentRootCollection = PersistentRootCollection::new());
+
+/// A heap-allocatable root that keeps a DOM object alive for as long as the
+/// `PersistentRoot` itself exists, independent of any stack scope.
+///
+/// Mirrors SpiderMonkey's `PersistentRooted` (see `RootingAPI.h`); unlike
+/// `Root<T>`, it is meant to be embedded in long-lived Rust structures
+/// (timers, caches, pending callbacks) that need to hold a DOM object
+/// without keeping a stack-bound `Root<T>` alive.
+///
+/// `entry` links this root into `PERSISTENT_ROOTS`'s intrusive list, boxed
+/// for the same reason as `Root<T>`'s `entry`: a stable heap address that
+/// survives the `PersistentRoot<T>` itself being moved.
+pub struct PersistentRoot<T: DomObject> {
+    entry: Box<PersistentRootListEntry>,
+    ptr: NonZero<*const T>,
+}
+
+impl<T: DomObject> PersistentRoot<T> {
+    /// Create a new persistent root for the provided DOM object.
+    pub fn new(obj: &T) -> PersistentRoot<T> {
+        assert_in_script();
+        let entry = Box::new(PersistentRootListEntry {
+            prev: Cell::new(ptr::null_mut()),
+            next: Cell::new(ptr::null_mut()),
+            reflector: unsafe { &*obj.reflector() },
+        });
+        unsafe {
+            PERSISTENT_ROOTS.with(|collection| collection.root(&*entry as *const _ as *mut _));
+        }
+        PersistentRoot {
+            entry: entry,
+            ptr: unsafe { NonZero::new_unchecked(obj) },
+        }
+    }
+}
+
+impl<T: DomObject> Deref for PersistentRoot<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        assert_in_script();
+        unsafe { &*self.ptr.get() }
+    }
+}
+
+impl<T: DomObject> Clone for PersistentRoot<T> {
+    fn clone(&self) -> PersistentRoot<T> {
+        // Additive, like `Root`: this registers a brand new entry rather
+        // than sharing the original's.
+        PersistentRoot::new(&*self)
+    }
+}
+
+impl<T: DomObject> Drop for PersistentRoot<T> {
+    fn drop(&mut self) {
+        unsafe {
+            PERSISTENT_ROOTS.with(|collection| collection.unroot(&mut *self.entry as *mut _));
+        }
+    }
+}
+
+impl<T: DomObject + HeapSizeOf> HeapSizeOf for PersistentRoot<T> {
+    fn heap_size_of_children(&self) -> usize {
+        (**self).heap_size_of_children()
+    }
+}
+
+unsafe impl<T: DomObject> JSTraceable for PersistentRoot<T> {
+    unsafe fn trace(&self, _: *mut JSTracer) {
+        // Already traced by `trace_persistent_roots`.
+    }
+}
+
+/// SM callback that traces every persistent root. Invoked alongside
+/// `trace_roots`.
+pub unsafe fn trace_persistent_roots(tracer: *mut JSTracer) {
+    debug!("tracing persistent roots");
+    PERSISTENT_ROOTS.with(|collection| {
+        let mut entry = collection.head.get();
+        while !entry.is_null() {
+            trace_reflector(tracer, "persistent", &*(*entry).reflector);
+            entry = (*entry).next.get();
+        }
+    });
+}
+
 /// Helper trait for safer manipulations of Option<Heap<T>> values.
 pub trait OptionalHeapSetter {
     type Value;
@@ -681,3 +1292,67 @@ impl<T: GCMethods + Copy> OptionalHeapSetter for Option<Heap<T>> where Heap<T>:
         self.as_ref().unwrap().set(v);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DummyTraceable;
+
+    unsafe impl JSTraceable for DummyTraceable {
+        unsafe fn trace(&self, _trc: *mut JSTracer) {}
+    }
+
+    fn new_entry(traceable: *const JSTraceable) -> TraceableRootListEntry {
+        TraceableRootListEntry {
+            prev: Cell::new(ptr::null_mut()),
+            next: Cell::new(ptr::null_mut()),
+            traceable: traceable,
+        }
+    }
+
+    // Splicing an entry out of the middle of the traceable list must patch
+    // its neighbours' prev/next pointers at each other, not just at the
+    // removed entry.
+    #[test]
+    fn unroot_traceable_non_lifo_removal() {
+        thread_state::initialize(thread_state::SCRIPT);
+        let collection = RootCollection::new();
+        let dummy = DummyTraceable;
+        let traceable: *const JSTraceable = &dummy;
+
+        let mut a = new_entry(traceable);
+        let mut b = new_entry(traceable);
+        let mut c = new_entry(traceable);
+
+        unsafe {
+            // Rooted in order A, B, C, so the list head-to-tail is C, B, A.
+            collection.root_traceable(&mut a as *mut _);
+            collection.root_traceable(&mut b as *mut _);
+            collection.root_traceable(&mut c as *mut _);
+
+            // Drop B out of the middle of the list.
+            collection.unroot_traceable(&mut b as *mut _);
+
+            // The list must now be C, A, with no dangling references to B
+            // anywhere, and the prev/next pointers on both sides of the gap
+            // must point at each other rather than at the removed entry.
+            let mut walked = vec![];
+            let mut cur = collection.traceable_head.get();
+            while !cur.is_null() {
+                walked.push(cur);
+                cur = (*cur).next.get();
+            }
+            assert_eq!(walked, vec![&mut c as *mut _, &mut a as *mut _]);
+
+            assert_eq!(c.prev.get(), ptr::null_mut());
+            assert_eq!(c.next.get(), &mut a as *mut _);
+            assert_eq!(a.prev.get(), &mut c as *mut _);
+            assert_eq!(a.next.get(), ptr::null_mut());
+
+            collection.unroot_traceable(&mut a as *mut _);
+            collection.unroot_traceable(&mut c as *mut _);
+            assert!(collection.traceable_head.get().is_null());
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The `Reflector` struct.
+
+use js::jsapi::{Heap, JSObject};
+use js::rust::HandleObject;
+use std::default::Default;
+
+/// Encapsulates the IDL reflector that ties a Rust DOM object to its
+/// corresponding `JSObject`.
+///
+/// This should only be used as a field in other DOM objects.
+#[must_root]
+pub struct Reflector {
+    object: Heap<*mut JSObject>,
+}
+
+impl Reflector {
+    /// Get the reflecting object.
+    pub fn get_jsobject(&self) -> HandleObject {
+        unsafe { HandleObject::from_marked_location(self.object.get() as *const _) }
+    }
+
+    /// Initialize the reflector. Can only be called once; panics on
+    /// subsequent calls, since the reflecting object is supposed to be set
+    /// up alongside the DOM object it belongs to, not rebound afterwards.
+    pub fn set_jsobject(&self, object: *mut JSObject) {
+        assert!(self.object.get().is_null());
+        assert!(!object.is_null());
+        self.object.set(object);
+    }
+
+    /// Create an uninitialized `Reflector`.
+    pub fn new() -> Reflector {
+        Reflector {
+            object: Heap::default(),
+        }
+    }
+}
+
+impl Default for Reflector {
+    fn default() -> Reflector {
+        Reflector::new()
+    }
+}
+
+/// A trait to provide access to the `Reflector` for a DOM object.
+pub trait DomObject {
+    /// Returns the receiver's reflector.
+    fn reflector(&self) -> &Reflector;
+}